@@ -0,0 +1,127 @@
+//
+// da_backend.rs
+// Copyright (C) 2023 db3.network Author imotai <codego.me@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use db3_error::{DB3Error, Result};
+use db3_storage::ar_fs::{ArFileSystem, ArFileSystemConfig};
+use ethers::types::U256;
+
+/// a pluggable data-availability target that a rollup can commit record batches to,
+/// so the rollup logic itself never has to know whether it's talking to Arweave,
+/// an S3-style gateway or anything else
+#[async_trait]
+pub trait DataAvailabilityBackend: Send + Sync {
+    /// compress and upload a record batch, returning the backend-specific
+    /// locator, the reward/cost paid, the number of rows and the compressed size
+    async fn compress_and_upload_record_batch(
+        &self,
+        last_id: String,
+        start_block: u64,
+        end_block: u64,
+        recordbatch: &RecordBatch,
+        network_id: u64,
+    ) -> Result<(String, U256, u64, u64)>;
+
+    /// fetch a previously uploaded record batch by its locator
+    async fn fetch(&self, id: &str) -> Result<RecordBatch>;
+}
+
+/// the original Arweave-backed data-availability target, driving the same
+/// compress/upload path `ArToolBox` used before it became backend-agnostic
+pub struct ArweaveBackend {
+    ar_filesystem: ArFileSystem,
+    temp_data_path: String,
+}
+
+impl ArweaveBackend {
+    pub fn new(ar_fs_config: ArFileSystemConfig, temp_data_path: String) -> Result<Self> {
+        let ar_filesystem = ArFileSystem::new(ar_fs_config)?;
+        Ok(Self {
+            ar_filesystem,
+            temp_data_path,
+        })
+    }
+}
+
+#[async_trait]
+impl DataAvailabilityBackend for ArweaveBackend {
+    async fn compress_and_upload_record_batch(
+        &self,
+        last_id: String,
+        start_block: u64,
+        end_block: u64,
+        recordbatch: &RecordBatch,
+        network_id: u64,
+    ) -> Result<(String, U256, u64, u64)> {
+        crate::ar_toolbox::ArToolBox::compress_and_upload_to_arweave(
+            &self.ar_filesystem,
+            self.temp_data_path.as_str(),
+            last_id,
+            start_block,
+            end_block,
+            recordbatch,
+            network_id,
+        )
+        .await
+    }
+
+    async fn fetch(&self, id: &str) -> Result<RecordBatch> {
+        crate::ar_toolbox::ArToolBox::fetch_from_arweave(&self.ar_filesystem, id).await
+    }
+}
+
+/// placeholder for an S3/IPFS-style gateway backend, for operators who want to
+/// mirror or replace Arweave as the DA target without touching rollup logic.
+/// NOT implemented yet: both trait methods unconditionally error, and
+/// `RollupExecutor::build_da_backend` refuses to construct this from a live
+/// config until a real client is wired up behind it
+pub struct GatewayBackend {
+    gateway_url: String,
+}
+
+impl GatewayBackend {
+    pub fn new(gateway_url: String) -> Self {
+        Self { gateway_url }
+    }
+}
+
+#[async_trait]
+impl DataAvailabilityBackend for GatewayBackend {
+    async fn compress_and_upload_record_batch(
+        &self,
+        _last_id: String,
+        _start_block: u64,
+        _end_block: u64,
+        _recordbatch: &RecordBatch,
+        _network_id: u64,
+    ) -> Result<(String, U256, u64, u64)> {
+        // the gateway client isn't wired up yet; fail the rollup step instead of
+        // panicking so a live `da_gateway_url` config can't take the node down
+        Err(DB3Error::RollupError(format!(
+            "the gateway DA backend for {} is not implemented yet",
+            self.gateway_url
+        )))
+    }
+
+    async fn fetch(&self, _id: &str) -> Result<RecordBatch> {
+        Err(DB3Error::RollupError(format!(
+            "the gateway DA backend for {} is not implemented yet",
+            self.gateway_url
+        )))
+    }
+}
@@ -15,23 +15,33 @@
 // limitations under the License.
 //
 
+use crate::metrics::{
+    INDEXER_APPLY_MUTATIONS_TOTAL, INDEXER_CONFIRMED_GAP, INDEXER_EVENT_TOTAL,
+    INDEXER_RECOVER_LAG, INDEXER_RUN_QUERY_LATENCY, INDEXER_SYNCED_BLOCK,
+};
 use crate::mutation_utils::MutationUtil;
 use crate::recover::{Recover, RecoverConfig};
 use crate::version_util;
+use base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine as _};
 use db3_crypto::db3_address::DB3Address;
 use db3_error::{DB3Error, Result};
 use db3_event::event_processor::EventProcessor;
 use db3_event::event_processor::EventProcessorConfig;
 use db3_proto::db3_base_proto::SystemStatus;
-use db3_proto::db3_database_v2_proto::BlockState;
+use db3_proto::db3_database_v2_proto::{BlockState, Document};
 use db3_proto::db3_indexer_proto::indexer_node_server::IndexerNode;
 use db3_proto::db3_indexer_proto::{
-    ContractSyncStatus, GetContractSyncStatusRequest, GetContractSyncStatusResponse,
-    GetSystemStatusRequest, RunQueryRequest, RunQueryResponse, SetupRequest, SetupResponse,
+    BatchQueryItem, BatchQueryResult, ContractSyncStatus, EventTaskRecord,
+    GetContractSyncStatusRequest, GetContractSyncStatusResponse, GetSystemStatusRequest,
+    ReindexContractRequest, ReindexContractResponse, RestartContractSyncRequest,
+    RestartContractSyncResponse, RunBatchQueryRequest, RunBatchQueryResponse, RunQueryRequest,
+    RunQueryResponse, SetupRequest, SetupResponse, StopContractSyncRequest,
+    StopContractSyncResponse,
 };
 use db3_proto::db3_mutation_v2_proto::MutationAction;
 use db3_proto::db3_storage_proto::block_response::MutationWrapper;
 use db3_proto::db3_storage_proto::event_message;
+use db3_proto::db3_storage_proto::BlockEvent;
 use db3_proto::db3_storage_proto::EventMessage as EventMessageV2;
 use db3_sdk::store_sdk_v2::StoreSDKV2;
 use db3_storage::db_store_v2::{DBStoreV2, DBStoreV2Config};
@@ -39,16 +49,28 @@ use db3_storage::key_store::{KeyStore, KeyStoreConfig};
 use db3_storage::state_store::{StateStore, StateStoreConfig};
 use ethers::abi::Address;
 use ethers::prelude::{LocalWallet, Signer};
+use ethers::providers::{Http, Provider};
 use std::collections::HashMap;
 use std::ops::Deref;
 use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
 use tokio::task;
-use tokio::time::{sleep, Duration};
+use tokio::task::AbortHandle;
+use tokio::time::{interval, sleep, Duration};
 use tonic::{Request, Response, Status};
 use tracing::{debug, info, warn};
 
 const MAX_BLOCK_ID: u64 = u64::MAX;
+// cap the number of queries a single run_batch_query call fans out to db_store at once,
+// so one oversized batch can't starve other requests of storage access
+const MAX_BATCH_QUERY_CONCURRENCY: usize = 16;
+// how often a running event task persists its last-synced block to the durable
+// registry, so a restart resumes close to where it left off rather than from genesis
+const EVENT_TASK_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+// never walk back further than this many blocks looking for a common ancestor;
+// a reorg deeper than this points at something worse than a chain reorg
+const MAX_REORG_SEARCH_DEPTH: u64 = 1024;
 #[derive(Clone)]
 pub struct IndexerNodeImpl {
     db_store: DBStoreV2,
@@ -61,6 +83,23 @@ pub struct IndexerNodeImpl {
     evm_node_url: String,
     processor_mapping: Arc<Mutex<HashMap<String, Arc<EventProcessor>>>>,
     admin_addr: String,
+    metrics_addr: String,
+    // shared EVM providers keyed by node url, so every processor targeting the same
+    // endpoint reuses one HTTP client/connection instead of opening its own
+    provider_pool: Arc<Mutex<HashMap<String, Arc<Provider<Http>>>>>,
+    // abort handles for each contract's spawned event task, keyed by contract addr,
+    // so stop/restart/reindex can cancel a running task instead of leaking it
+    event_task_handles: Arc<Mutex<HashMap<String, AbortHandle>>>,
+    // durable registry of started event tasks, so `recover_state` can resume each
+    // task from its last-synced block instead of rescanning from genesis
+    state_store: Arc<StateStore>,
+    // only apply mutations for blocks at least this many blocks below the chain
+    // head, so a shallow reorg never needs anything already applied to be undone
+    confirmation_depth: Arc<AtomicU64>,
+    // latest block_id seen in a BlockEvent, used to report the confirmed/head gap
+    head_block: Arc<AtomicU64>,
+    // latest block_id mutations have actually been applied up to
+    confirmed_block: Arc<AtomicU64>,
 }
 
 impl IndexerNodeImpl {
@@ -75,8 +114,12 @@ impl IndexerNodeImpl {
         evm_node_url: String,
         admin_addr: String,
         recover_data_path: String,
+        metrics_addr: String,
+        state_store_config: StateStoreConfig,
+        confirmation_depth: u64,
     ) -> Result<Self> {
         let db_store = DBStoreV2::new(config.clone())?;
+        let state_store = Arc::new(StateStore::new(state_store_config)?);
         let network_id = Arc::new(AtomicU64::new(network_id));
         let chain_id = Arc::new(AtomicU32::new(chain_id));
         let recover_config = RecoverConfig {
@@ -96,20 +139,70 @@ impl IndexerNodeImpl {
             key_root_path,
             contract_addr,
             evm_node_url,
-            //TODO recover from the database
+            // populated by `recover_state`, which starts a task for every event db in
+            // `db_store` and resumes it from the block the durable event task registry
+            // holds for it; the registry does not decide which tasks are started
             processor_mapping: Arc::new(Mutex::new(HashMap::new())),
             admin_addr,
+            metrics_addr,
+            provider_pool: Arc::new(Mutex::new(HashMap::new())),
+            event_task_handles: Arc::new(Mutex::new(HashMap::new())),
+            state_store,
+            confirmation_depth: Arc::new(AtomicU64::new(confirmation_depth)),
+            head_block: Arc::new(AtomicU64::new(0)),
+            confirmed_block: Arc::new(AtomicU64::new(0)),
         })
     }
 
+    /// hand out a cached, reference-counted provider for `evm_node_url`, creating one
+    /// on first use, so every processor targeting the same endpoint shares one HTTP
+    /// client and its connection/backoff state instead of opening a socket each.
+    /// only http(s) urls are supported today; a ws(s) url is rejected here with a
+    /// clear error rather than failing inside `Provider::<Http>::try_from` with a
+    /// message that doesn't explain why
+    fn get_or_create_provider(&self, evm_node_url: &str) -> Result<Arc<Provider<Http>>> {
+        if !(evm_node_url.starts_with("http://") || evm_node_url.starts_with("https://")) {
+            return Err(DB3Error::WriteStoreError(format!(
+                "evm_node_url {evm_node_url} is not http(s); websocket event sources are not supported yet"
+            )));
+        }
+        match self.provider_pool.lock() {
+            Ok(mut pool) => {
+                if let Some(provider) = pool.get(evm_node_url) {
+                    return Ok(provider.clone());
+                }
+                let provider = Arc::new(Provider::<Http>::try_from(evm_node_url).map_err(|e| {
+                    DB3Error::WriteStoreError(format!("fail to create the evm provider {e}"))
+                })?);
+                pool.insert(evm_node_url.to_string(), provider.clone());
+                Ok(provider)
+            }
+            _ => Err(DB3Error::WriteStoreError(
+                "fail to lock the provider pool".to_string(),
+            )),
+        }
+    }
+
     pub async fn recover_state(&self) -> Result<()> {
         self.db_store.recover_db_state()?;
         let databases = self.db_store.get_all_event_db()?;
+        let last_synced_blocks: HashMap<String, u64> = self
+            .state_store
+            .get_all_event_task_records()?
+            .into_iter()
+            .map(|r| (r.contract_addr, r.start_block))
+            .collect();
         for database in databases {
             let address_ref: &[u8] = database.address.as_ref();
             let db_address = DB3Address::try_from(address_ref)?;
             let (collections, _) = self.db_store.get_collection_of_database(&db_address)?;
             let tables = collections.iter().map(|c| c.name.to_string()).collect();
+            // resume from the last block this contract had synced to, instead of
+            // rescanning from genesis on every restart
+            let start_block = last_synced_blocks
+                .get(database.contract_address.as_str())
+                .copied()
+                .unwrap_or(0);
             if let Err(_e) = self
                 .start_an_event_task(
                     &db_address,
@@ -117,13 +210,17 @@ impl IndexerNodeImpl {
                     database.events_json_abi.as_str(),
                     &tables,
                     database.contract_address.as_str(),
-                    0,
+                    start_block,
                 )
                 .await
             {
                 info!("recover the event db {} has error", db_address.to_hex());
             } else {
-                info!("recover the event db {} done", db_address.to_hex());
+                info!(
+                    "recover the event db {} from block {} done",
+                    db_address.to_hex(),
+                    start_block
+                );
             }
         }
         Ok(())
@@ -133,6 +230,15 @@ impl IndexerNodeImpl {
     /// 1. subscribe db3 event
     /// 2. handle event to sync db3 node block
     pub async fn start(&self, store_sdk: StoreSDKV2) -> Result<()> {
+        if !self.metrics_addr.is_empty() {
+            let metrics_addr = self.metrics_addr.clone();
+            task::spawn(async move {
+                if let Err(e) = crate::metrics::start_metrics_server(metrics_addr.as_str()).await
+                {
+                    warn!("fail to start the metrics server for {e}");
+                }
+            });
+        }
         self.recover_state().await?;
         self.recover_from_ar().await?;
         self.recover_from_fetched_blocks(&store_sdk).await?;
@@ -230,24 +336,105 @@ impl IndexerNodeImpl {
                     "Receive BlockEvent: Block\t{}\tMutationCount\t{}",
                     be.block_id, be.mutation_count,
                 );
-                let block_state = match self.db_store.recover_block_state()? {
+                self.head_block.store(be.block_id, Ordering::Relaxed);
+                let mut block_state = match self.db_store.recover_block_state()? {
                     Some(block_state) => block_state,
                     None => BlockState { block: 0, order: 0 },
                 };
+                INDEXER_RECOVER_LAG
+                    .with_label_values(&["db3_node"])
+                    .set((be.block_id.saturating_sub(block_state.block)) as i64);
+
+                if let Some(parent_height) = self.reorg_suspected(&be)? {
+                    let common_ancestor =
+                        self.find_common_ancestor(store_sdk, parent_height).await?;
+                    if common_ancestor < block_state.block {
+                        warn!(
+                            "detected a reorg below block {}, rewinding db_store to block {}",
+                            block_state.block, common_ancestor
+                        );
+                        self.db_store.rewind_state(common_ancestor)?;
+                        block_state = BlockState {
+                            block: common_ancestor,
+                            order: 0,
+                        };
+                    }
+                }
+                // always record the hash for the block this event is actually about,
+                // so the next event's parent hash can be checked against the right height
+                self.state_store
+                    .put_block_hash(be.block_id, be.block_hash.clone())?;
+
+                let confirmation_depth = self.confirmation_depth.load(Ordering::Relaxed);
+                let confirmed_head = be.block_id.saturating_sub(confirmation_depth);
+                INDEXER_CONFIRMED_GAP
+                    .with_label_values(&["db3_node"])
+                    .set(be.block_id.saturating_sub(confirmed_head) as i64);
+                if confirmed_head <= block_state.block {
+                    // nothing newly confirmed yet, wait for more confirmation blocks; still
+                    // report a rewound block_state so a reorg is immediately observable
+                    // instead of leaving the stale pre-reorg value in place
+                    self.confirmed_block
+                        .store(block_state.block, Ordering::Relaxed);
+                    return Ok(());
+                }
 
                 let response = store_sdk
-                    .get_blocks(block_state.block, be.block_id)
+                    .get_blocks(block_state.block, confirmed_head)
                     .await
                     .map_err(|e| DB3Error::WriteStoreError(format!("{e}")))?
                     .into_inner();
                 let mutations = response.mutations;
                 debug!("Block mutations size: {:?}", mutations.len());
                 self.parse_and_apply_mutations(&mutations).await?;
+                self.confirmed_block
+                    .store(confirmed_head, Ordering::Relaxed);
             }
             _ => {}
         }
         Ok(())
     }
+
+    /// check whether `be`'s parent hash disagrees with what we recorded for
+    /// `be.block_id - 1` when that block was itself the head; returns that
+    /// height so the caller can search for a common ancestor below it
+    fn reorg_suspected(&self, be: &BlockEvent) -> Result<Option<u64>> {
+        if be.block_id == 0 || be.parent_hash.is_empty() {
+            return Ok(None);
+        }
+        let parent_height = be.block_id - 1;
+        match self.state_store.get_block_hash(parent_height)? {
+            Some(stored_hash) if stored_hash != be.parent_hash => Ok(Some(parent_height)),
+            _ => Ok(None),
+        }
+    }
+
+    /// walk back from `from_block` comparing our own recorded block hash against what
+    /// the chain reports today, to find the last block both agree on; bounded by
+    /// `MAX_REORG_SEARCH_DEPTH` since a reorg deeper than that isn't recoverable here
+    async fn find_common_ancestor(
+        &self,
+        store_sdk: &StoreSDKV2,
+        from_block: u64,
+    ) -> Result<u64> {
+        let mut block = from_block;
+        let floor = from_block.saturating_sub(MAX_REORG_SEARCH_DEPTH);
+        while block > floor {
+            block -= 1;
+            let stored_hash = match self.state_store.get_block_hash(block)? {
+                Some(hash) => hash,
+                None => continue,
+            };
+            let (chain_hash, _) = store_sdk
+                .get_block_header(block)
+                .await
+                .map_err(|e| DB3Error::WriteStoreError(format!("{e}")))?;
+            if stored_hash == chain_hash {
+                return Ok(block);
+            }
+        }
+        Ok(floor)
+    }
     fn build_wallet(key_root_path: &str) -> Result<LocalWallet> {
         let config = KeyStoreConfig {
             key_root_path: key_root_path.to_string(),
@@ -281,6 +468,7 @@ impl IndexerNodeImpl {
         contract_address: &str,
         start_block: u64,
     ) -> Result<()> {
+        let provider = self.get_or_create_provider(evm_node_url)?;
         let config = EventProcessorConfig {
             evm_node_url: evm_node_url.to_string(),
             db_addr: db.to_hex(),
@@ -290,7 +478,7 @@ impl IndexerNodeImpl {
             start_block,
         };
         let processor = Arc::new(
-            EventProcessor::new(config, self.db_store.clone())
+            EventProcessor::new_with_provider(config, self.db_store.clone(), provider)
                 .await
                 .map_err(|e| DB3Error::WriteStoreError(format!("{e}")))?,
         );
@@ -309,19 +497,110 @@ impl IndexerNodeImpl {
             _ => todo!(),
         }
 
-        task::spawn(async move {
-            if let Err(e) = processor
-                .start()
-                .await
-                .map_err(|e| DB3Error::WriteStoreError(format!("{e}")))
-            {
-                warn!("fail to start event processor for {e}");
+        self.state_store.add_event_task_record(&EventTaskRecord {
+            db_addr: db.to_hex(),
+            evm_node_url: evm_node_url.to_string(),
+            abi: abi.to_string(),
+            target_events: tables.iter().map(|t| t.to_string()).collect(),
+            contract_addr: contract_address.to_string(),
+            start_block,
+        })?;
+
+        let state_store = self.state_store.clone();
+        let contract_addr_for_task = contract_address.to_string();
+        let join_handle = task::spawn(async move {
+            let start_fut = processor.start();
+            tokio::pin!(start_fut);
+            let mut checkpoint = interval(EVENT_TASK_CHECKPOINT_INTERVAL);
+            loop {
+                tokio::select! {
+                    res = &mut start_fut => {
+                        if let Err(e) = res.map_err(|e| DB3Error::WriteStoreError(format!("{e}"))) {
+                            warn!("fail to start event processor for {e}");
+                        }
+                        break;
+                    }
+                    _ = checkpoint.tick() => {
+                        let block_number = processor.get_block_number();
+                        INDEXER_SYNCED_BLOCK
+                            .with_label_values(&[contract_addr_for_task.as_str()])
+                            .set(block_number as i64);
+                        INDEXER_EVENT_TOTAL
+                            .with_label_values(&[contract_addr_for_task.as_str()])
+                            .set(processor.get_event_number() as i64);
+                        if let Err(e) = state_store
+                            .update_event_task_block(contract_addr_for_task.as_str(), block_number)
+                        {
+                            warn!(
+                                "fail to checkpoint the synced block for {contract_addr_for_task}: {e}"
+                            );
+                        }
+                    }
+                }
             }
         });
+        match self.event_task_handles.lock() {
+            Ok(mut handles) => {
+                handles.insert(contract_address.to_string(), join_handle.abort_handle());
+            }
+            _ => todo!(),
+        }
         Ok(())
     }
 
+    /// cancel the spawned task for `contract_addr` and drop it from the live mapping,
+    /// returning the config it was running with so callers can restart or reindex it
+    fn stop_event_task(&self, contract_addr: &str) -> Result<EventProcessorConfig> {
+        let processor = match self.processor_mapping.lock() {
+            Ok(mut mapping) => mapping.remove(contract_addr).ok_or_else(|| {
+                DB3Error::WriteStoreError(format!("contract_addr {} not found", contract_addr))
+            })?,
+            _ => todo!(),
+        };
+        let config = processor.get_config().clone();
+        match self.event_task_handles.lock() {
+            Ok(mut handles) => {
+                if let Some(handle) = handles.remove(contract_addr) {
+                    handle.abort();
+                }
+            }
+            _ => todo!(),
+        }
+        // drop the durable record too, so the stopped task's progress is no longer
+        // tracked; this does not keep the contract stopped across a restart:
+        // recover_state starts every event db again, and a contract without a
+        // record starts from block 0
+        self.state_store.remove_event_task_record(contract_addr)?;
+        Ok(config)
+    }
+
+    /// the block the running task last checkpointed to the durable registry, which
+    /// `EventProcessorConfig.start_block` (captured once at task creation and never
+    /// mutated) does not reflect — callers that need to resume from live progress
+    /// rather than from genesis must read this before `stop_event_task` drops the
+    /// record
+    fn last_checkpointed_block(&self, contract_addr: &str) -> Result<Option<u64>> {
+        Ok(self
+            .state_store
+            .get_all_event_task_records()?
+            .into_iter()
+            .find(|r| r.contract_addr == contract_addr)
+            .map(|r| r.start_block))
+    }
+
     async fn parse_and_apply_mutations(&self, mutations: &Vec<MutationWrapper>) -> Result<()> {
+        let result = self.parse_and_apply_mutations_inner(mutations).await;
+        let label = if result.is_ok() { "success" } else { "failure" };
+        INDEXER_APPLY_MUTATIONS_TOTAL
+            .with_label_values(&[label])
+            .inc();
+        result
+    }
+
+    async fn parse_and_apply_mutations_inner(
+        &self,
+        mutations: &Vec<MutationWrapper>,
+    ) -> Result<()> {
         for mutation in mutations.iter() {
             let header = mutation.header.as_ref().unwrap();
             let body = mutation.body.as_ref().unwrap();
@@ -363,16 +642,34 @@ impl IndexerNode for IndexerNodeImpl {
         let status_list: Vec<ContractSyncStatus> = match self.processor_mapping.lock() {
             Ok(mapping) => mapping
                 .iter()
-                .map(|ref processor| ContractSyncStatus {
-                    addr: processor.1.get_config().contract_addr.to_string(),
-                    evm_node_url: processor.1.get_config().evm_node_url.to_string(),
-                    block_number: processor.1.get_block_number(),
-                    event_number: processor.1.get_event_number(),
+                .map(|ref processor| {
+                    let contract_addr = processor.1.get_config().contract_addr.to_string();
+                    let block_number = processor.1.get_block_number();
+                    let event_number = processor.1.get_event_number();
+                    INDEXER_SYNCED_BLOCK
+                        .with_label_values(&[contract_addr.as_str()])
+                        .set(block_number as i64);
+                    INDEXER_EVENT_TOTAL
+                        .with_label_values(&[contract_addr.as_str()])
+                        .set(event_number as i64);
+                    ContractSyncStatus {
+                        addr: contract_addr,
+                        evm_node_url: processor.1.get_config().evm_node_url.to_string(),
+                        block_number,
+                        event_number,
+                    }
                 })
                 .collect(),
             _ => todo!(),
         };
-        Ok(Response::new(GetContractSyncStatusResponse { status_list }))
+        let head_block = self.head_block.load(Ordering::Relaxed);
+        let confirmed_block = self.confirmed_block.load(Ordering::Relaxed);
+        Ok(Response::new(GetContractSyncStatusResponse {
+            status_list,
+            head_block,
+            confirmed_block,
+            confirmed_gap: head_block.saturating_sub(confirmed_block),
+        }))
     }
 
     async fn setup(
@@ -431,10 +728,41 @@ impl IndexerNode for IndexerNodeImpl {
         })?;
         if let Some(q) = &r.query {
             info!("query str {} q {:?}", q.query_str, q);
-            let (documents, count) = self
+            let cursor = if r.page_token.is_empty() {
+                None
+            } else {
+                Some(Self::decode_page_token(r.page_token.as_str())?)
+            };
+            // fetch one extra row so we can tell whether another page follows,
+            // without ever materializing more than `limit + 1` documents
+            let fetch_limit = if r.limit > 0 {
+                Some(r.limit.saturating_add(1))
+            } else {
+                None
+            };
+            let timer = INDEXER_RUN_QUERY_LATENCY
+                .with_label_values(&[r.db.as_str()])
+                .start_timer();
+            let (mut documents, count) = self
                 .db_store
-                .query_docs(&addr, r.col_name.as_str(), q)
+                .query_docs_page(
+                    &addr,
+                    r.col_name.as_str(),
+                    q,
+                    cursor.as_deref(),
+                    fetch_limit,
+                )
                 .map_err(|e| Status::internal(format!("{e}")))?;
+            timer.observe_duration();
+            let next_page_token = if r.limit > 0 && documents.len() as u32 > r.limit {
+                documents.truncate(r.limit as usize);
+                documents
+                    .last()
+                    .map(|d| Self::encode_page_token(&Self::doc_cursor(d)))
+                    .unwrap_or_default()
+            } else {
+                String::new()
+            };
             info!(
                 "query str {} from collection {} in db {} with result len {}, parameters len {}",
                 q.query_str,
@@ -443,11 +771,286 @@ impl IndexerNode for IndexerNodeImpl {
                 documents.len(),
                 q.parameters.len()
             );
-            Ok(Response::new(RunQueryResponse { documents, count }))
+            Ok(Response::new(RunQueryResponse {
+                documents,
+                count,
+                next_page_token,
+            }))
         } else {
             Err(Status::invalid_argument("no query provided".to_string()))
         }
     }
+
+    async fn run_batch_query(
+        &self,
+        request: Request<RunBatchQueryRequest>,
+    ) -> std::result::Result<Response<RunBatchQueryResponse>, Status> {
+        let r = request.into_inner();
+        let semaphore = Arc::new(Semaphore::new(MAX_BATCH_QUERY_CONCURRENCY));
+        let mut tasks = Vec::with_capacity(r.queries.len());
+        for item in r.queries.into_iter() {
+            let db_store = self.db_store.clone();
+            let semaphore = semaphore.clone();
+            tasks.push(task::spawn(async move {
+                let _permit = semaphore
+                    .acquire_owned()
+                    .await
+                    .expect("the batch query semaphore is never closed");
+                Self::run_single_query(&db_store, &item)
+            }));
+        }
+        let mut results = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            let result = match task.await {
+                Ok(result) => result,
+                Err(e) => BatchQueryResult {
+                    documents: vec![],
+                    count: 0,
+                    error: format!("query task panicked: {e}"),
+                },
+            };
+            results.push(result);
+        }
+        Ok(Response::new(RunBatchQueryResponse { results }))
+    }
+
+    async fn stop_contract_sync(
+        &self,
+        request: Request<StopContractSyncRequest>,
+    ) -> std::result::Result<Response<StopContractSyncResponse>, Status> {
+        let r = request.into_inner();
+        self.stop_event_task(r.contract_addr.as_str())
+            .map_err(|e| Status::internal(format!("{e}")))?;
+        info!("stopped contract sync for {}", r.contract_addr);
+        Ok(Response::new(StopContractSyncResponse {}))
+    }
+
+    async fn restart_contract_sync(
+        &self,
+        request: Request<RestartContractSyncRequest>,
+    ) -> std::result::Result<Response<RestartContractSyncResponse>, Status> {
+        let r = request.into_inner();
+        // read the live checkpoint before stop_event_task removes the durable
+        // record, otherwise we'd only have config.start_block, which is frozen
+        // at whatever block the task was first created at
+        let resume_block = self.last_checkpointed_block(r.contract_addr.as_str())?;
+        let config = self
+            .stop_event_task(r.contract_addr.as_str())
+            .map_err(|e| Status::internal(format!("{e}")))?;
+        let db = DB3Address::from_hex(config.db_addr.as_str())
+            .map_err(|e| Status::internal(format!("fail to parse the db address for {e}")))?;
+        self.start_an_event_task(
+            &db,
+            config.evm_node_url.as_str(),
+            config.abi.as_str(),
+            &config.target_events,
+            config.contract_addr.as_str(),
+            resume_block.unwrap_or(config.start_block),
+        )
+        .await
+        .map_err(|e| Status::internal(format!("{e}")))?;
+        info!("restarted contract sync for {}", r.contract_addr);
+        Ok(Response::new(RestartContractSyncResponse {}))
+    }
+
+    async fn reindex_contract(
+        &self,
+        request: Request<ReindexContractRequest>,
+    ) -> std::result::Result<Response<ReindexContractResponse>, Status> {
+        let r = request.into_inner();
+        let config = self
+            .stop_event_task(r.contract_addr.as_str())
+            .map_err(|e| Status::internal(format!("{e}")))?;
+        let db = DB3Address::from_hex(config.db_addr.as_str())
+            .map_err(|e| Status::internal(format!("fail to parse the db address for {e}")))?;
+        self.start_an_event_task(
+            &db,
+            config.evm_node_url.as_str(),
+            config.abi.as_str(),
+            &config.target_events,
+            config.contract_addr.as_str(),
+            r.from_block,
+        )
+        .await
+        .map_err(|e| Status::internal(format!("{e}")))?;
+        info!(
+            "reindexing contract {} from block {}",
+            r.contract_addr, r.from_block
+        );
+        Ok(Response::new(ReindexContractResponse {}))
+    }
 }
+
+impl IndexerNodeImpl {
+    /// the deterministic key a page token is compared against: the document's internal
+    /// id, which `query_docs` already returns in a stable order
+    fn doc_cursor(doc: &Document) -> Vec<u8> {
+        doc.id.clone()
+    }
+
+    /// encode the cursor of the last document on a page into an opaque page token
+    fn encode_page_token(cursor: &[u8]) -> String {
+        BASE64_STANDARD.encode(cursor)
+    }
+
+    /// decode a page token back into the cursor it was issued for, rejecting a token
+    /// that is not valid base64; any valid base64 token is accepted as a cursor
+    fn decode_page_token(token: &str) -> std::result::Result<Vec<u8>, Status> {
+        BASE64_STANDARD
+            .decode(token)
+            .map_err(|e| Status::invalid_argument(format!("invalid page token: {e}")))
+    }
+
+    /// run a single query of a batch, turning every failure into a `BatchQueryResult.error`
+    /// instead of a `Status` so one malformed query doesn't fail the whole batch
+    fn run_single_query(db_store: &DBStoreV2, item: &BatchQueryItem) -> BatchQueryResult {
+        let addr = match DB3Address::from_hex(item.db.as_str()) {
+            Ok(addr) => addr,
+            Err(e) => {
+                return BatchQueryResult {
+                    documents: vec![],
+                    count: 0,
+                    error: format!("fail to parse the db address for {e}"),
+                }
+            }
+        };
+        let query = match &item.query {
+            Some(q) => q,
+            None => {
+                return BatchQueryResult {
+                    documents: vec![],
+                    count: 0,
+                    error: "no query provided".to_string(),
+                }
+            }
+        };
+        let timer = INDEXER_RUN_QUERY_LATENCY
+            .with_label_values(&[item.db.as_str()])
+            .start_timer();
+        let result = db_store.query_docs(&addr, item.col_name.as_str(), query);
+        timer.observe_duration();
+        match result {
+            Ok((documents, count)) => BatchQueryResult {
+                documents,
+                count,
+                error: String::new(),
+            },
+            Err(e) => BatchQueryResult {
+                documents: vec![],
+                count: 0,
+                error: format!("{e}"),
+            },
+        }
+    }
+}
+
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+    use db3_storage::doc_store::DocStoreConfig;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    async fn new_test_indexer(real_path: &str, confirmation_depth: u64) -> IndexerNodeImpl {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let key_root_path = path
+            .parent()
+            .unwrap()
+            .parent()
+            .unwrap()
+            .join("tools/keys")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let db_store_config = DBStoreV2Config {
+            db_path: format!("{real_path}/db_store"),
+            db_store_cf_name: "db".to_string(),
+            doc_store_cf_name: "doc".to_string(),
+            collection_store_cf_name: "cf2".to_string(),
+            index_store_cf_name: "index".to_string(),
+            doc_owner_store_cf_name: "doc_owner".to_string(),
+            db_owner_store_cf_name: "db_owner".to_string(),
+            scan_max_limit: 50,
+            enable_doc_store: false,
+            doc_store_conf: DocStoreConfig::default(),
+            doc_start_id: 1000,
+        };
+        let state_store_config = StateStoreConfig {
+            db_path: format!("{real_path}/state_store"),
+        };
+        IndexerNodeImpl::new(
+            db_store_config,
+            1,
+            1,
+            "http://127.0.0.1:26659".to_string(),
+            "http://127.0.0.1:1984".to_string(),
+            key_root_path,
+            "".to_string(),
+            "http://127.0.0.1:8545".to_string(),
+            "".to_string(),
+            format!("{real_path}/recover"),
+            "".to_string(),
+            state_store_config,
+            confirmation_depth,
+        )
+        .await
+        .unwrap()
+    }
+
+    // handle_event stores each event's block hash at its own block height, not at the
+    // (much lower, since confirmation_depth is large here) confirmed head. this test
+    // stores the hashes the same way and checks that reorg_suspected then reads a
+    // real extension of the chain as no reorg and a mismatched parent hash as one.
+    #[tokio::test]
+    async fn test_reorg_hashes_keyed_by_actual_block_height() {
+        let tmp_dir = TempDir::new("indexer_reorg_test").expect("create temp dir");
+        let real_path = tmp_dir.path().to_str().unwrap().to_string();
+        let indexer = new_test_indexer(real_path.as_str(), 1000).await;
+
+        let event1 = BlockEvent {
+            block_id: 5,
+            mutation_count: 0,
+            block_hash: vec![5, 5, 5],
+            parent_hash: vec![4, 4, 4],
+        };
+        // nothing recorded for block 4 yet, so no reorg can be asserted
+        assert_eq!(indexer.reorg_suspected(&event1).unwrap(), None);
+        indexer
+            .state_store
+            .put_block_hash(event1.block_id, event1.block_hash.clone())
+            .unwrap();
+
+        let event2 = BlockEvent {
+            block_id: 6,
+            mutation_count: 0,
+            block_hash: vec![6, 6, 6],
+            parent_hash: vec![5, 5, 5],
+        };
+        // block 6's parent hash matches what we recorded for block 5: no reorg
+        assert_eq!(indexer.reorg_suspected(&event2).unwrap(), None);
+        indexer
+            .state_store
+            .put_block_hash(event2.block_id, event2.block_hash.clone())
+            .unwrap();
+
+        // each block's hash must be retrievable at its own height, not squashed
+        // into the confirmed head (which is 0 for both events here)
+        assert_eq!(
+            indexer.state_store.get_block_hash(5).unwrap(),
+            Some(vec![5, 5, 5])
+        );
+        assert_eq!(
+            indexer.state_store.get_block_hash(6).unwrap(),
+            Some(vec![6, 6, 6])
+        );
+
+        let event3 = BlockEvent {
+            block_id: 7,
+            mutation_count: 0,
+            block_hash: vec![7, 7, 7],
+            parent_hash: vec![9, 9, 9],
+        };
+        // block 7 disagrees with the recorded hash for block 6: a real reorg at 6
+        assert_eq!(indexer.reorg_suspected(&event3).unwrap(), Some(6));
+    }
+}
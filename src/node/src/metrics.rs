@@ -0,0 +1,108 @@
+//
+// metrics.rs
+// Copyright (C) 2023 db3.network Author imotai <codego.me@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use db3_error::{DB3Error, Result};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, Encoder,
+    HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+use std::net::SocketAddr;
+use tracing::{info, warn};
+
+lazy_static! {
+    /// the block number each contract's event processor has synced to
+    pub static ref INDEXER_SYNCED_BLOCK: IntGaugeVec = register_int_gauge_vec!(
+        "indexer_synced_block",
+        "the last block number synced per contract",
+        &["contract_addr"]
+    )
+    .unwrap();
+
+    /// the total number of events seen per contract; exported as a gauge since the
+    /// event processor already tracks the cumulative count itself
+    pub static ref INDEXER_EVENT_TOTAL: IntGaugeVec = register_int_gauge_vec!(
+        "indexer_event_total",
+        "the total number of events processed per contract",
+        &["contract_addr"]
+    )
+    .unwrap();
+
+    /// the gap between the latest block seen from db3 and the recovered block state,
+    /// used to alert on a stalled indexer
+    pub static ref INDEXER_RECOVER_LAG: IntGaugeVec = register_int_gauge_vec!(
+        "indexer_recover_lag",
+        "the gap between the latest block seen and the recovered block state",
+        &["source"]
+    )
+    .unwrap();
+
+    /// success/failure counts for applying a batch of mutations
+    pub static ref INDEXER_APPLY_MUTATIONS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "indexer_apply_mutations_total",
+        "the number of parse_and_apply_mutations calls by result",
+        &["result"]
+    )
+    .unwrap();
+
+    /// run_query latency, in seconds
+    pub static ref INDEXER_RUN_QUERY_LATENCY: HistogramVec = register_histogram_vec!(
+        "indexer_run_query_latency_seconds",
+        "the latency of run_query calls",
+        &["db"]
+    )
+    .unwrap();
+
+    /// the number of blocks between the chain head and the confirmed head, i.e.
+    /// `confirmation_depth` (or the head itself while it is still below that depth);
+    /// a rewind after a reorg does not change it, the rewound block is reported by
+    /// the `GetContractSyncStatus` RPC instead
+    pub static ref INDEXER_CONFIRMED_GAP: IntGaugeVec = register_int_gauge_vec!(
+        "indexer_confirmed_gap",
+        "the gap between the chain head and the confirmed head",
+        &["source"]
+    )
+    .unwrap();
+}
+
+async fn serve_metrics(_req: Request<Body>) -> std::result::Result<Response<Body>, hyper::Error> {
+    let encoder = TextEncoder::new();
+    let metric_families = prometheus::gather();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&metric_families, &mut buffer) {
+        warn!("fail to encode metrics {e}");
+    }
+    Ok(Response::new(Body::from(buffer)))
+}
+
+/// start a lightweight HTTP listener that exposes the Prometheus metrics
+/// above in text format, so operators can scrape and alert on indexer health
+/// without polling the `GetContractSyncStatus` RPC
+pub async fn start_metrics_server(addr: &str) -> Result<()> {
+    let socket_addr: SocketAddr = addr
+        .parse()
+        .map_err(|e| DB3Error::WriteStoreError(format!("invalid metrics listen addr {e}")))?;
+    let make_svc =
+        make_service_fn(|_conn| async { Ok::<_, hyper::Error>(service_fn(serve_metrics)) });
+    info!("start the metrics server on {addr}");
+    Server::bind(&socket_addr)
+        .serve(make_svc)
+        .await
+        .map_err(|e| DB3Error::WriteStoreError(format!("metrics server error {e}")))
+}
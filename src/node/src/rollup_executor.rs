@@ -16,19 +16,30 @@
 //
 
 use crate::ar_toolbox::ArToolBox;
+use crate::da_backend::{ArweaveBackend, DataAvailabilityBackend};
 use arc_swap::ArcSwapOption;
 use db3_base::times;
 use db3_error::{DB3Error, Result};
-use db3_proto::db3_rollup_proto::{GcRecord, RollupRecord};
-use db3_storage::ar_fs::{ArFileSystem, ArFileSystemConfig};
+use db3_proto::db3_rollup_proto::{
+    GcRecord, PendingRollupRecord, RollupPart, RollupRecord, SnapshotRecord,
+};
+use db3_storage::ar_fs::ArFileSystemConfig;
+use db3_storage::db_store_v2::DBStoreV2;
 use db3_storage::meta_store_client::MetaStoreClient;
 use db3_storage::mutation_store::MutationStore;
 use db3_storage::system_store::{SystemRole, SystemStore};
+use std::future::Future;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Instant;
+use tokio::time::{sleep, Duration};
 use tracing::{info, warn};
 
+// estimate the raw memory size of a rollup range by converting only this many
+// mutations to a RecordBatch and scaling up, instead of converting the whole
+// (potentially huge, if rollup has fallen behind) range just to size it
+const MEMORY_SIZE_SAMPLE_MUTATIONS: usize = 256;
+
 #[derive(Clone)]
 pub struct RollupExecutorConfig {
     pub temp_data_path: String,
@@ -38,6 +49,7 @@ pub struct RollupExecutorConfig {
 pub struct RollupExecutor {
     config: RollupExecutorConfig,
     storage: MutationStore,
+    db_store: DBStoreV2,
     ar_toolbox: ArcSwapOption<ArToolBox>,
     min_rollup_size: Arc<AtomicU64>,
     meta_store: ArcSwapOption<MetaStoreClient>,
@@ -49,6 +61,10 @@ pub struct RollupExecutor {
     system_store: Arc<SystemStore>,
     rollup_max_interval: Arc<AtomicU64>,
     min_gc_round_offset: Arc<AtomicU64>,
+    min_snapshot_round_offset: Arc<AtomicU64>,
+    max_retry_count: Arc<AtomicU64>,
+    retry_base_backoff_ms: Arc<AtomicU64>,
+    max_rollup_part_bytes: Arc<AtomicU64>,
 }
 
 unsafe impl Sync for RollupExecutor {}
@@ -58,6 +74,7 @@ impl RollupExecutor {
     pub async fn new(
         config: RollupExecutorConfig,
         storage: MutationStore,
+        db_store: DBStoreV2,
         system_store: Arc<SystemStore>,
     ) -> Result<Self> {
         if let Some(c) = system_store.get_config(&SystemRole::DataRollupNode)? {
@@ -71,19 +88,20 @@ impl RollupExecutor {
                 MetaStoreClient::new(c.contract_addr.as_str(), c.evm_node_url.as_str(), wallet)
                     .await?,
             )));
-            let ar_fs_config = ArFileSystemConfig {
-                arweave_url: c.ar_node_url.clone(),
-                key_root_path: config.key_root_path.clone(),
-            };
-            let ar_filesystem = ArFileSystem::new(ar_fs_config)?;
+            let da_backend = Self::build_da_backend(
+                &c,
+                config.key_root_path.as_str(),
+                config.temp_data_path.as_str(),
+            )?;
             let ar_toolbox = ArcSwapOption::from(Some(Arc::new(ArToolBox::new(
-                ar_filesystem,
+                da_backend,
                 config.temp_data_path.clone(),
             )?)));
             let rollup_max_interval = Arc::new(AtomicU64::new(c.rollup_max_interval));
             Ok(Self {
                 config,
                 storage,
+                db_store,
                 ar_toolbox,
                 min_rollup_size: Arc::new(AtomicU64::new(min_rollup_size)),
                 meta_store,
@@ -95,12 +113,17 @@ impl RollupExecutor {
                 system_store,
                 rollup_max_interval,
                 min_gc_round_offset: Arc::new(AtomicU64::new(c.min_gc_offset)),
+                min_snapshot_round_offset: Arc::new(AtomicU64::new(c.min_snapshot_offset)),
+                max_retry_count: Arc::new(AtomicU64::new(c.max_retry_count)),
+                retry_base_backoff_ms: Arc::new(AtomicU64::new(c.retry_base_backoff_ms)),
+                max_rollup_part_bytes: Arc::new(AtomicU64::new(c.max_rollup_part_bytes)),
             })
         } else {
             let rollup_max_interval = Arc::new(AtomicU64::new(0));
             Ok(Self {
                 config,
                 storage,
+                db_store,
                 ar_toolbox: ArcSwapOption::from(None),
                 min_rollup_size: Arc::new(AtomicU64::new(0)),
                 meta_store: ArcSwapOption::from(None),
@@ -112,10 +135,40 @@ impl RollupExecutor {
                 system_store,
                 rollup_max_interval,
                 min_gc_round_offset: Arc::new(AtomicU64::new(0)),
+                min_snapshot_round_offset: Arc::new(AtomicU64::new(0)),
+                max_retry_count: Arc::new(AtomicU64::new(0)),
+                retry_base_backoff_ms: Arc::new(AtomicU64::new(0)),
+                max_rollup_part_bytes: Arc::new(AtomicU64::new(u64::MAX)),
             })
         }
     }
 
+    /// pick the data-availability backend to roll up to from the system config,
+    /// so operators can choose or mirror DA targets without touching rollup logic
+    fn build_da_backend(
+        c: &db3_proto::db3_base_proto::SystemConfig,
+        key_root_path: &str,
+        temp_data_path: &str,
+    ) -> Result<Box<dyn DataAvailabilityBackend>> {
+        if c.da_gateway_url.is_empty() {
+            let ar_fs_config = ArFileSystemConfig {
+                arweave_url: c.ar_node_url.clone(),
+                key_root_path: key_root_path.to_string(),
+            };
+            Ok(Box::new(ArweaveBackend::new(
+                ar_fs_config,
+                temp_data_path.to_string(),
+            )?))
+        } else {
+            // GatewayBackend is a placeholder: its methods always return an error,
+            // there is no working client behind them yet. Reject the config up front
+            // instead of silently accepting a da_gateway_url that can never rollup.
+            Err(DB3Error::RollupError(
+                "da_gateway_url is set but the gateway DA backend is not implemented yet; leave it empty to use Arweave".to_string(),
+            ))
+        }
+    }
+
     ///
     /// call by the update hook
     ///
@@ -136,14 +189,22 @@ impl RollupExecutor {
             ));
             self.min_gc_round_offset
                 .store(c.min_gc_offset, Ordering::Relaxed);
+            self.min_snapshot_round_offset
+                .store(c.min_snapshot_offset, Ordering::Relaxed);
+            self.max_retry_count
+                .store(c.max_retry_count, Ordering::Relaxed);
+            self.retry_base_backoff_ms
+                .store(c.retry_base_backoff_ms, Ordering::Relaxed);
+            self.max_rollup_part_bytes
+                .store(c.max_rollup_part_bytes, Ordering::Relaxed);
             self.meta_store.store(meta_store);
-            let ar_fs_config = ArFileSystemConfig {
-                arweave_url: c.ar_node_url.clone(),
-                key_root_path: self.config.key_root_path.clone(),
-            };
-            let ar_filesystem = ArFileSystem::new(ar_fs_config)?;
+            let da_backend = Self::build_da_backend(
+                &c,
+                self.config.key_root_path.as_str(),
+                self.config.temp_data_path.as_str(),
+            )?;
             let ar_toolbox = Some(Arc::new(ArToolBox::new(
-                ar_filesystem,
+                da_backend,
                 self.config.temp_data_path.clone(),
             )?));
             self.ar_toolbox.store(ar_toolbox);
@@ -152,6 +213,51 @@ impl RollupExecutor {
         Ok(())
     }
 
+    /// transient network/rpc hiccups against Arweave or the EVM chain are worth
+    /// retrying; anything that signals a permanently bad request is not
+    fn is_retryable(e: &DB3Error) -> bool {
+        !matches!(e, DB3Error::InvalidParameter(_))
+    }
+
+    /// retry a transient Arweave/EVM call with exponential backoff, bounded by
+    /// `max_retry_count`; fatal errors (anything `is_retryable` rejects) are
+    /// returned immediately
+    async fn retry_with_backoff<T, F, Fut>(&self, op_name: &str, mut f: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let max_retry = self.max_retry_count.load(Ordering::Relaxed);
+        let base_backoff_ms = self.retry_base_backoff_ms.load(Ordering::Relaxed).max(1);
+        let mut attempt = 0_u64;
+        loop {
+            match f().await {
+                Ok(v) => return Ok(v),
+                Err(e) if !Self::is_retryable(&e) || attempt >= max_retry => {
+                    warn!("{op_name} failed permanently after {attempt} retries: {e}");
+                    return Err(e);
+                }
+                Err(e) => {
+                    let backoff_ms = base_backoff_ms.saturating_mul(1_u64 << attempt.min(16));
+                    warn!(
+                        "{op_name} failed on attempt {attempt}, retrying in {backoff_ms}ms: {e}"
+                    );
+                    sleep(Duration::from_millis(backoff_ms)).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// only mutations already covered by a committed snapshot can be reclaimed,
+    /// otherwise a recovering node would have no way to rebuild the state in between
+    fn snapshot_covers(&self, end_block: u64) -> Result<bool> {
+        match self.storage.get_last_snapshot_record()? {
+            Some(s) => Ok(s.end_block >= end_block),
+            None => Ok(false),
+        }
+    }
+
     fn gc_mutation(&self) -> Result<()> {
         let (last_start_block, last_end_block, first) = match self.storage.get_last_gc_record()? {
             Some(r) => (r.start_block, r.end_block, false),
@@ -170,6 +276,13 @@ impl RollupExecutor {
         )? {
             if first {
                 if let Some(r) = self.storage.get_rollup_record(last_start_block)? {
+                    if !self.snapshot_covers(r.end_block)? {
+                        info!(
+                            "skip gc for block range [{}, {}) until a snapshot covers it",
+                            r.start_block, r.end_block
+                        );
+                        return Ok(());
+                    }
                     self.storage.gc_range_mutation(r.start_block, r.end_block)?;
                     let record = GcRecord {
                         start_block: r.start_block,
@@ -194,6 +307,13 @@ impl RollupExecutor {
                 }
             } else {
                 if let Some(r) = self.storage.get_next_rollup_record(last_start_block)? {
+                    if !self.snapshot_covers(r.end_block)? {
+                        info!(
+                            "skip gc for block range [{}, {}) until a snapshot covers it",
+                            r.start_block, r.end_block
+                        );
+                        return Ok(());
+                    }
                     self.storage.gc_range_mutation(r.start_block, r.end_block)?;
                     let record = GcRecord {
                         start_block: r.start_block,
@@ -223,6 +343,88 @@ impl RollupExecutor {
         }
     }
 
+    /// materialize the current document/collection state into a snapshot and
+    /// upload it through the DA backend so a node can bootstrap without
+    /// replaying the whole rollup history
+    async fn take_snapshot(&self, end_block: u64) -> Result<()> {
+        if let Some(ref ar_toolbox) = self.ar_toolbox.load_full() {
+            let last_end_block = match self.storage.get_last_snapshot_record()? {
+                Some(r) => r.end_block,
+                None => 0_u64,
+            };
+            if !self.storage.has_enough_round_left(
+                last_end_block,
+                self.min_snapshot_round_offset.load(Ordering::Relaxed),
+            )? {
+                info!("not enough round to take a new snapshot");
+                return Ok(());
+            }
+            let now = Instant::now();
+            let network_id = self.network_id.load(Ordering::Relaxed);
+            let state_recordbatch = self.db_store.export_state_recordbatch()?;
+            let (id, _reward, _num_rows, size) = self
+                .retry_with_backoff("upload state snapshot to the DA backend", || {
+                    ar_toolbox.compress_and_upload_record_batch(
+                        "".to_string(),
+                        last_end_block,
+                        end_block,
+                        &state_recordbatch,
+                        network_id,
+                    )
+                })
+                .await?;
+            let record = SnapshotRecord {
+                start_block: last_end_block,
+                end_block,
+                arweave_tx: id,
+                data_size: size,
+                time: times::get_current_time_in_secs(),
+                processed_time: now.elapsed().as_secs(),
+            };
+            self.storage
+                .add_snapshot_record(&record)
+                .map_err(|e| DB3Error::RollupError(format!("{e}")))?;
+            info!(
+                "snapshot the state up to block {} done with data size {} and processed time {}",
+                end_block,
+                size,
+                now.elapsed().as_secs()
+            );
+            Ok(())
+        } else {
+            warn!("the system has not been setup, please setup it first");
+            Ok(())
+        }
+    }
+
+    /// rebuild storage from the latest snapshot plus the mutations committed after its `end_block`,
+    /// used by a fresh or recovering node to bootstrap without replaying the entire rollup history
+    pub async fn restore_from_snapshot(&self) -> Result<()> {
+        if let Some(ref ar_toolbox) = self.ar_toolbox.load_full() {
+            if let Some(snapshot) = self.storage.get_last_snapshot_record()? {
+                info!(
+                    "restore state from snapshot [{}, {}) with tx {}",
+                    snapshot.start_block, snapshot.end_block, snapshot.arweave_tx
+                );
+                let state_recordbatch = ar_toolbox.fetch(snapshot.arweave_tx.as_str()).await?;
+                self.db_store.restore_state_recordbatch(&state_recordbatch)?;
+                let mutations = self
+                    .storage
+                    .get_range_mutations(snapshot.end_block, self.storage.get_current_block()?)?;
+                for mutation in mutations.iter() {
+                    self.db_store.replay_mutation(mutation)?;
+                }
+                info!("restore from snapshot done");
+            } else {
+                info!("no snapshot found, nothing to restore");
+            }
+            Ok(())
+        } else {
+            warn!("the system has not been setup, please setup it first");
+            Ok(())
+        }
+    }
+
     pub fn get_pending_rollup(&self) -> RollupRecord {
         RollupRecord {
             end_block: self.pending_end_block.load(Ordering::Relaxed),
@@ -231,6 +433,7 @@ impl RollupExecutor {
             compress_data_size: 0,
             processed_time: 0,
             arweave_tx: "".to_string(),
+            parts: vec![],
             time: times::get_current_time_in_secs(),
             mutation_count: self.pending_mutations.load(Ordering::Relaxed),
             cost: 0,
@@ -273,8 +476,15 @@ impl RollupExecutor {
             }
             self.pending_mutations
                 .store(mutations.len() as u64, Ordering::Relaxed);
-            let recordbatch = ar_toolbox.convert_mutations_to_recordbatch(&mutations)?;
-            let memory_size = recordbatch.get_array_memory_size();
+            // estimate the raw memory size from a bounded sample instead of converting
+            // the whole range to a RecordBatch, so sizing a far-behind rollup never
+            // requires more memory than sizing one that's fully caught up
+            let sample_len = mutations.len().min(MEMORY_SIZE_SAMPLE_MUTATIONS);
+            let sample_recordbatch =
+                ar_toolbox.convert_mutations_to_recordbatch(&mutations[..sample_len])?;
+            let bytes_per_mutation =
+                sample_recordbatch.get_array_memory_size() as f64 / sample_len.max(1) as f64;
+            let memory_size = (bytes_per_mutation * mutations.len() as f64) as usize;
             self.pending_data_size
                 .store(memory_size as u64, Ordering::Relaxed);
             if memory_size < self.min_rollup_size.load(Ordering::Relaxed) as usize {
@@ -291,30 +501,107 @@ impl RollupExecutor {
                 self.pending_data_size.store(0, Ordering::Relaxed);
                 self.pending_mutations.store(0, Ordering::Relaxed);
             }
-            let (id, reward, num_rows, size) = ar_toolbox
-                .compress_and_upload_record_batch(
-                    tx,
-                    last_end_block,
-                    current_block,
-                    &recordbatch,
-                    network_id,
-                )
-                .await?;
-            let (evm_cost, tx_hash) = meta_store
-                .update_rollup_step(id.as_str(), network_id)
+            // resume from the parts already uploaded before a restart, rather than
+            // redoing already-completed (and already-paid-for) uploads. Keyed only on
+            // `start_block` matching: `current_block` commonly moves between a crash
+            // and the next `process()` call once new mutations land while the node was
+            // down, and that drift must not throw away parts that already landed.
+            let mut parts: Vec<RollupPart> = match self.storage.get_pending_rollup_record()? {
+                Some(p) if p.start_block == last_end_block => {
+                    info!(
+                        "resume pending rollup starting at block {} with {} part(s) already uploaded",
+                        p.start_block,
+                        p.parts.len()
+                    );
+                    p.parts
+                }
+                _ => {
+                    self.storage.add_pending_rollup_record(&PendingRollupRecord {
+                        start_block: last_end_block,
+                        end_block: current_block,
+                        parts: vec![],
+                    })?;
+                    vec![]
+                }
+            };
+            // mutations already folded into an uploaded part must be skipped rather
+            // than re-chunked: the chunk boundaries below are only recomputed over
+            // whatever mutations remain, so they stay correct even when `current_block`
+            // (and therefore the total mutation count) grew since those parts landed
+            let already_uploaded_rows: u64 = parts.iter().map(|p| p.mutation_count).sum();
+            let remaining_mutations =
+                &mutations[(already_uploaded_rows as usize).min(mutations.len())..];
+
+            let mut num_rows = already_uploaded_rows;
+            if !remaining_mutations.is_empty() {
+                let max_part_bytes =
+                    self.max_rollup_part_bytes.load(Ordering::Relaxed).max(1) as usize;
+                let remaining_memory_size = (bytes_per_mutation
+                    * remaining_mutations.len() as f64) as usize;
+                let num_parts = (remaining_memory_size / max_part_bytes + 1)
+                    .min(remaining_mutations.len().max(1));
+                let chunk_len = (remaining_mutations.len() + num_parts - 1) / num_parts;
+
+                for chunk in remaining_mutations.chunks(chunk_len.max(1)) {
+                    let last_id = parts
+                        .last()
+                        .map(|p| p.locator.clone())
+                        .unwrap_or_else(|| tx.clone());
+                    let part_recordbatch = ar_toolbox.convert_mutations_to_recordbatch(chunk)?;
+                    let (id, part_reward, part_rows, part_size) = self
+                        .retry_with_backoff("upload rollup part to the DA backend", || {
+                            ar_toolbox.compress_and_upload_record_batch(
+                                last_id.clone(),
+                                last_end_block,
+                                current_block,
+                                &part_recordbatch,
+                                network_id,
+                            )
+                        })
+                        .await?;
+                    num_rows += part_rows;
+                    parts.push(RollupPart {
+                        locator: id,
+                        size: part_size,
+                        mutation_count: chunk.len() as u64,
+                        reward: part_reward,
+                    });
+                    self.storage.add_pending_rollup_record(&PendingRollupRecord {
+                        start_block: last_end_block,
+                        end_block: current_block,
+                        parts: parts.clone(),
+                    })?;
+                }
+            }
+            // sum from the full, persisted part list rather than just the parts
+            // uploaded in this call, so a crash-resume doesn't lose the reward
+            // already earned for parts a prior attempt uploaded
+            let reward: u64 = parts.iter().map(|p| p.reward).sum();
+            let compress_data_size: u64 = parts.iter().map(|p| p.size).sum();
+            let last_locator = parts
+                .last()
+                .map(|p| p.locator.clone())
+                .unwrap_or_else(|| tx.clone());
+            let (evm_cost, tx_hash) = self
+                .retry_with_backoff("commit rollup step to the EVM chain", || {
+                    meta_store.update_rollup_step(last_locator.as_str(), network_id)
+                })
                 .await?;
             let tx_str = format!("0x{}", hex::encode(tx_hash.as_bytes()));
-            info!("the process rollup done with num mutations {num_rows}, raw data size {memory_size}, compress data size {size} and processed time {} id {} ar cost {} and evm tx {} and cost {}", now.elapsed().as_secs(),
-                id.as_str(), reward,
+            info!("the process rollup done with num mutations {num_rows}, raw data size {memory_size}, compress data size {compress_data_size} in {} part(s) and processed time {} id {} ar cost {} and evm tx {} and cost {}",
+                parts.len(),
+                now.elapsed().as_secs(),
+                last_locator.as_str(), reward,
                 tx_str.as_str(),
                 evm_cost.as_u64()
                 );
             let record = RollupRecord {
                 end_block: current_block,
                 raw_data_size: memory_size as u64,
-                compress_data_size: size,
+                compress_data_size,
                 processed_time: now.elapsed().as_secs(),
-                arweave_tx: id,
+                arweave_tx: last_locator,
+                parts,
                 time: times::get_current_time_in_secs(),
                 mutation_count: num_rows,
                 cost: reward,
@@ -325,6 +612,8 @@ impl RollupExecutor {
             self.storage
                 .add_rollup_record(&record)
                 .map_err(|e| DB3Error::RollupError(format!("{e}")))?;
+            self.storage.clear_pending_rollup_record()?;
+            self.take_snapshot(current_block).await?;
             self.gc_mutation()?;
         } else {
             warn!("the system has not been setup, please setup it first");
@@ -416,12 +705,13 @@ mod tests {
     async fn setup_for_smoke_test() -> Result<RollupExecutor> {
         let tmp_dir_path = TempDir::new("add_store_path").expect("create temp dir");
         let real_path = tmp_dir_path.path().to_str().unwrap().to_string();
-        let (state_config, system_store_config, store_config, rollup_config, _) =
+        let (state_config, system_store_config, store_config, rollup_config, db_store_config) =
             generate_config(real_path.as_str());
         let state_store = Arc::new(StateStore::new(state_config).unwrap());
         let system_store = Arc::new(SystemStore::new(system_store_config, state_store.clone()));
         let storage = MutationStore::new(store_config).unwrap();
         storage.recover().unwrap();
+        let db_store = DBStoreV2::new(db_store_config).unwrap();
         let system_config = SystemConfig {
             min_rollup_size: 1024,
             rollup_interval: 1000,
@@ -432,6 +722,11 @@ mod tests {
             rollup_max_interval: 2000,
             contract_addr: "0x5FbDB2315678afecb367f032d93F642f64180aa3".to_string(),
             min_gc_offset: 100,
+            min_snapshot_offset: 100,
+            da_gateway_url: "".to_string(),
+            max_retry_count: 3,
+            retry_base_backoff_ms: 200,
+            max_rollup_part_bytes: 8 * 1024 * 1024,
         };
         let result = system_store.update_config(&SystemRole::DataRollupNode, &system_config);
         assert_eq!(true, result.is_ok());
@@ -454,7 +749,7 @@ mod tests {
             );
             assert_eq!(true, result.is_ok());
         }
-        RollupExecutor::new(rollup_config, storage, system_store).await
+        RollupExecutor::new(rollup_config, storage, db_store, system_store).await
     }
 
     #[tokio::test]